@@ -1,81 +1,224 @@
-use crate::user::User;
-use reqwest::header::CONTENT_TYPE;
-use serde::Serialize;
+use cloudflare::framework::endpoint::{Endpoint, Method};
+use cloudflare::framework::response::ApiResult;
+use serde::{Deserialize, Serialize};
 
-#[derive(Serialize)]
+use crate::http;
+use crate::http_client::ApiClient;
+use crate::settings::global_user::GlobalUser;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Route {
-    enabled: Option<bool>,
-    script: Option<String>,
+    pub id: Option<String>,
+    pub pattern: String,
+    pub script: Option<String>,
+}
+
+impl ApiResult for Route {}
+impl ApiResult for Vec<Route> {}
+
+#[derive(Serialize, Clone)]
+struct RouteConfig {
     pattern: String,
+    script: Option<String>,
+    enabled: Option<bool>,
 }
 
 impl Route {
-    pub fn create(user: User, script: Option<String>) -> Result<Route, failure::Error> {
+    /// Create `pattern` (optionally associated with `script`) against
+    /// `zone_id`. `multiscript` is the account's multi-script-enabled flag:
+    /// such an account must supply `script` and goes through
+    /// `/workers/routes`, while a single-script account always goes through
+    /// the legacy `/filters` endpoint (and has any `script` name ignored) --
+    /// that's reconciled here so callers don't need to know which API
+    /// generation their account is on.
+    pub fn create(
+        user: &GlobalUser,
+        zone_id: &str,
+        pattern: &str,
+        script: Option<String>,
+        multiscript: bool,
+    ) -> Result<Route, failure::Error> {
         println!("Creating a route...");
-        if user.account.multiscript {
-            match script {
-                Some(s) => multi_script(user, s),
-                None => failure::bail!("⚠️ You must provide the name of the script you'd like to associate with this route."),
-            }
-        } else {
-            if script.is_some() {
-                println!("⚠️ You only have a single script account. Ignoring name.");
-            }
-            single_script(user)
+        let client = http::cf_v4_client(user)?;
+        Route::create_with_client(&client, zone_id, pattern, script, multiscript)
+    }
+
+    pub(crate) fn create_with_client<C: ApiClient>(
+        client: &C,
+        zone_id: &str,
+        pattern: &str,
+        script: Option<String>,
+        multiscript: bool,
+    ) -> Result<Route, failure::Error> {
+        if multiscript {
+            let script = script.ok_or_else(|| {
+                failure::format_err!(
+                    "⚠️ You must provide the name of the script you'd like to associate with this route."
+                )
+            })?;
+            return client
+                .request(&CreateRoute {
+                    zone_identifier: zone_id,
+                    body: RouteConfig {
+                        pattern: pattern.to_string(),
+                        script: Some(script),
+                        enabled: None,
+                    },
+                })
+                .map(|success| success.result);
         }
+
+        if script.is_some() {
+            println!("⚠️ You only have a single script account. Ignoring name.");
+        }
+        client
+            .request(&CreateFilter {
+                zone_identifier: zone_id,
+                body: RouteConfig {
+                    pattern: pattern.to_string(),
+                    script: None,
+                    enabled: Some(true),
+                },
+            })
+            .map(|success| success.result)
+    }
+
+    /// List every route configured on `zone_id`.
+    pub fn list(user: &GlobalUser, zone_id: &str) -> Result<Vec<Route>, failure::Error> {
+        let client = http::cf_v4_client(user)?;
+        Route::list_with_client(&client, zone_id)
+    }
+
+    pub(crate) fn list_with_client<C: ApiClient>(
+        client: &C,
+        zone_id: &str,
+    ) -> Result<Vec<Route>, failure::Error> {
+        client
+            .request(&ListRoutes {
+                zone_identifier: zone_id,
+            })
+            .map(|success| success.result)
+    }
+
+    /// Delete the route identified by `route_id` on `zone_id`.
+    pub fn delete(user: &GlobalUser, zone_id: &str, route_id: &str) -> Result<(), failure::Error> {
+        let client = http::cf_v4_client(user)?;
+        Route::delete_with_client(&client, zone_id, route_id)
+    }
+
+    pub(crate) fn delete_with_client<C: ApiClient>(
+        client: &C,
+        zone_id: &str,
+        route_id: &str,
+    ) -> Result<(), failure::Error> {
+        client
+            .request(&DeleteRoute {
+                zone_identifier: zone_id,
+                route_identifier: route_id,
+            })
+            .map(|_| ())
+    }
+}
+
+struct ListRoutes<'a> {
+    zone_identifier: &'a str,
+}
+
+impl<'a> Endpoint<Vec<Route>> for ListRoutes<'a> {
+    fn method(&self) -> Method {
+        Method::Get
+    }
+    fn path(&self) -> String {
+        format!("zones/{}/workers/routes", self.zone_identifier)
+    }
+}
+
+struct CreateRoute<'a> {
+    zone_identifier: &'a str,
+    body: RouteConfig,
+}
+
+impl<'a> Endpoint<Route, (), RouteConfig> for CreateRoute<'a> {
+    fn method(&self) -> Method {
+        Method::Put
+    }
+    fn path(&self) -> String {
+        format!("zones/{}/workers/routes", self.zone_identifier)
+    }
+    fn body(&self) -> Option<RouteConfig> {
+        Some(self.body.clone())
+    }
+}
+
+struct DeleteRoute<'a> {
+    zone_identifier: &'a str,
+    route_identifier: &'a str,
+}
+
+impl<'a> Endpoint<Route> for DeleteRoute<'a> {
+    fn method(&self) -> Method {
+        Method::Delete
+    }
+    fn path(&self) -> String {
+        format!(
+            "zones/{}/workers/routes/{}",
+            self.zone_identifier, self.route_identifier
+        )
     }
 }
 
-fn multi_script(user: User, script: String) -> Result<Route, failure::Error> {
-    let pattern = &user.settings.clone().project.route.expect("⚠️ Your project config has an error, check your `wrangler.toml`: `route` must be provided.");
-    let route = Route {
-        script: Some(script),
-        pattern: pattern.to_string(),
-        enabled: None,
-    };
-    let zone_id = &user.settings.project.zone_id;
-    let routes_addr = format!(
-        "https://api.cloudflare.com/client/v4/zones/{}/workers/routes",
-        zone_id
-    );
-
-    let client = reqwest::Client::new();
-    let settings = user.settings;
-
-    client
-        .put(&routes_addr)
-        .header("X-Auth-Key", settings.global_user.api_key)
-        .header("X-Auth-Email", settings.global_user.email)
-        .header(CONTENT_TYPE, "application/json")
-        .body(serde_json::to_string(&route)?)
-        .send()?;
-
-    Ok(route)
+/// The legacy endpoint single-script accounts use in place of
+/// `/workers/routes`. Kept behind the same `Route::create` entry point so
+/// callers don't need to know which API generation their account is on.
+struct CreateFilter<'a> {
+    zone_identifier: &'a str,
+    body: RouteConfig,
+}
+
+impl<'a> Endpoint<Route, (), RouteConfig> for CreateFilter<'a> {
+    fn method(&self) -> Method {
+        Method::Put
+    }
+    fn path(&self) -> String {
+        format!("zones/{}/workers/filters", self.zone_identifier)
+    }
+    fn body(&self) -> Option<RouteConfig> {
+        Some(self.body.clone())
+    }
 }
 
-fn single_script(user: User) -> Result<Route, failure::Error> {
-    let pattern = user.settings.clone().project.route.expect("⚠️ Your project config has an error, check your `wrangler.toml`: `route` must be provided.");
-    let route = Route {
-        script: None,
-        pattern,
-        enabled: Some(true),
-    };
-    let zone_id = &user.settings.project.zone_id;
-    let filters_addr = format!(
-        "https://api.cloudflare.com/client/v4/zones/{}/workers/filters",
-        zone_id
-    );
-
-    let client = reqwest::Client::new();
-    let settings = user.settings;
-
-    client
-        .put(&filters_addr)
-        .header("X-Auth-Key", settings.global_user.api_key)
-        .header("X-Auth-Email", settings.global_user.email)
-        .header(CONTENT_TYPE, "application/json")
-        .body(serde_json::to_string(&route)?)
-        .send()?;
-
-    Ok(route)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http_client::{MockApiClient, MockResponse};
+
+    #[test]
+    fn create_surfaces_api_errors_on_a_non_2xx_response() {
+        let client = MockApiClient::new(vec![MockResponse::Err(
+            "route pattern already exists".to_string(),
+        )]);
+
+        let err = Route::create_with_client(&client, "zone123", "example.com/*", Some("my-script".to_string()), true)
+            .unwrap_err();
+
+        assert!(err.to_string().contains("route pattern already exists"));
+    }
+
+    #[test]
+    fn create_requires_a_script_name_for_a_multiscript_account() {
+        let client = MockApiClient::new(vec![]);
+
+        let err = Route::create_with_client(&client, "zone123", "example.com/*", None, true).unwrap_err();
+
+        assert!(err.to_string().contains("must provide the name of the script"));
+    }
+
+    #[test]
+    fn list_returns_an_empty_vec_when_no_routes_are_configured() {
+        let client = MockApiClient::new(vec![MockResponse::Ok(serde_json::json!([]))]);
+
+        let routes = Route::list_with_client(&client, "zone123").unwrap();
+
+        assert!(routes.is_empty());
+    }
 }
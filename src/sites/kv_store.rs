@@ -0,0 +1,361 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use cloudflare::endpoints::workerskv::remove_bulk::RemoveBulk;
+use cloudflare::endpoints::workerskv::write_bulk::{KeyValuePair, WriteBulk};
+use cloudflare::framework::endpoint::{Endpoint, Method};
+use cloudflare::framework::response::ApiResult;
+use cloudflare::framework::HttpApiClient;
+use indicatif::{MultiProgress, ProgressBar};
+use serde::Deserialize;
+
+use crate::http;
+use crate::http_client::ApiClient;
+use crate::settings::global_user::GlobalUser;
+use crate::settings::toml::Target;
+
+use super::store::{progress_style, SiteStore};
+
+// Workers KV bulk write/delete endpoints cap a single request at 10,000 pairs
+// or 100MB, whichever comes first.
+const KV_BULK_MAX_PAIRS: usize = 10_000;
+const KV_BULK_MAX_BYTES: usize = 100 * 1024 * 1024;
+
+// How many bulk requests we'll have in flight at once. Cloudflare's API can
+// handle more, but this keeps us from hammering it (and the user's network)
+// on a deploy with hundreds of thousands of assets.
+const MAX_CONCURRENT_REQUESTS: usize = 10;
+
+const MAX_RETRIES: usize = 3;
+
+/// The default `SiteStore`: Workers KV, accessed through the bulk
+/// write/delete endpoints. Generic over `ApiClient` so tests can swap in a
+/// `MockApiClient` instead of the real `HttpApiClient`.
+pub struct KvStore<C: ApiClient = HttpApiClient> {
+    account_id: String,
+    namespace_id: String,
+    client: C,
+}
+
+impl KvStore<HttpApiClient> {
+    pub fn new(target: Target, user: GlobalUser, namespace_id: String) -> Result<KvStore<HttpApiClient>, failure::Error> {
+        Ok(KvStore::with_client(target.account_id, namespace_id, http::cf_v4_client(&user)?))
+    }
+}
+
+impl<C: ApiClient> KvStore<C> {
+    pub(crate) fn with_client(account_id: String, namespace_id: String, client: C) -> KvStore<C> {
+        KvStore {
+            account_id,
+            namespace_id,
+            client,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct KvKey {
+    name: String,
+}
+
+impl ApiResult for Vec<KvKey> {}
+
+impl<C: ApiClient + Sync> SiteStore for KvStore<C> {
+    fn list_keys(&self) -> Result<HashSet<String>, failure::Error> {
+        let mut keys = HashSet::new();
+        let mut cursor: Option<String> = None;
+
+        loop {
+            let response = self.client.request(&ListKeys {
+                account_identifier: &self.account_id,
+                namespace_identifier: &self.namespace_id,
+                cursor: cursor.clone(),
+            })?;
+
+            keys.extend(response.result.into_iter().map(|key| key.name));
+
+            match response.cursor {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+
+        Ok(keys)
+    }
+
+    fn bulk_put(&self, pairs: Vec<KeyValuePair>, multi_progress: &MultiProgress) -> Result<(), failure::Error> {
+        let progress = ProgressBar::new(pairs.len() as u64);
+        progress.set_style(progress_style("pairs uploaded"));
+        let progress = multi_progress.add(progress);
+
+        self.run_batches(chunk_pairs(pairs), &progress, |client, account_id, namespace_id, batch| {
+            let len = batch.len();
+            with_exponential_backoff(|| {
+                client
+                    .request(&WriteBulk {
+                        account_identifier: account_id,
+                        namespace_identifier: namespace_id,
+                        bulk_key_value_pairs: batch.clone(),
+                    })
+                    .map(|_| ())
+            })?;
+            Ok(len)
+        })?;
+
+        progress.finish_and_clear();
+        Ok(())
+    }
+
+    fn bulk_delete(&self, keys: Vec<String>, multi_progress: &MultiProgress) -> Result<(), failure::Error> {
+        let progress = ProgressBar::new(keys.len() as u64);
+        progress.set_style(progress_style("keys deleted"));
+        let progress = multi_progress.add(progress);
+
+        self.run_batches(chunk_keys(keys), &progress, |client, account_id, namespace_id, batch| {
+            let len = batch.len();
+            with_exponential_backoff(|| {
+                client
+                    .request(&RemoveBulk {
+                        account_identifier: account_id,
+                        namespace_identifier: namespace_id,
+                        bulk_keys: batch.clone(),
+                    })
+                    .map(|_| ())
+            })?;
+            Ok(len)
+        })?;
+
+        progress.finish_and_clear();
+        Ok(())
+    }
+}
+
+impl<C: ApiClient + Sync> KvStore<C> {
+    /// Run `batches` across a bounded pool of worker threads against Workers
+    /// KV's bulk endpoints, incrementing `progress` as each batch finishes.
+    /// Errors from individual batches are collected and surfaced together
+    /// once all batches have run, so one failing batch doesn't abort the
+    /// others.
+    fn run_batches<T, F>(&self, batches: Vec<Vec<T>>, progress: &ProgressBar, f: F) -> Result<(), failure::Error>
+    where
+        T: Clone + Send,
+        F: Fn(&C, &str, &str, Vec<T>) -> Result<usize, failure::Error> + Sync,
+    {
+        let errors: Arc<Mutex<Vec<failure::Error>>> = Arc::new(Mutex::new(Vec::new()));
+
+        thread::scope(|scope| {
+            for group in batches.chunks(MAX_CONCURRENT_REQUESTS) {
+                let handles: Vec<_> = group
+                    .iter()
+                    .map(|batch| {
+                        let batch = batch.clone();
+                        let errors = Arc::clone(&errors);
+                        let f = &f;
+                        scope.spawn(move || match f(&self.client, &self.account_id, &self.namespace_id, batch) {
+                            Ok(len) => progress.inc(len as u64),
+                            Err(e) => errors.lock().unwrap().push(e),
+                        })
+                    })
+                    .collect();
+                for handle in handles {
+                    let _ = handle.join();
+                }
+            }
+        });
+
+        let errors = Arc::try_unwrap(errors).unwrap().into_inner().unwrap();
+        if !errors.is_empty() {
+            let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            failure::bail!(
+                "{} batch(es) failed while syncing with Workers KV:\n{}",
+                messages.len(),
+                messages.join("\n")
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Lists the keys in a namespace a page at a time, mirroring the real
+/// `GET .../storage/kv/namespaces/:id/keys` endpoint -- defined here rather
+/// than pulled in from elsewhere so `list_keys` can go through `ApiClient`
+/// like every other Workers KV call and stay mockable in tests.
+struct ListKeys<'a> {
+    account_identifier: &'a str,
+    namespace_identifier: &'a str,
+    cursor: Option<String>,
+}
+
+impl<'a> Endpoint<Vec<KvKey>> for ListKeys<'a> {
+    fn method(&self) -> Method {
+        Method::Get
+    }
+    fn path(&self) -> String {
+        let base = format!(
+            "accounts/{}/storage/kv/namespaces/{}/keys",
+            self.account_identifier, self.namespace_identifier
+        );
+        match &self.cursor {
+            Some(cursor) => format!("{}?cursor={}", base, cursor),
+            None => base,
+        }
+    }
+}
+
+/// Split `pairs` into batches that each stay under both the pair-count and
+/// payload-size limits of the Workers KV bulk write endpoint.
+fn chunk_pairs(pairs: Vec<KeyValuePair>) -> Vec<Vec<KeyValuePair>> {
+    let mut batches = Vec::new();
+    let mut batch = Vec::new();
+    let mut batch_bytes = 0;
+
+    for pair in pairs {
+        let pair_bytes = pair.key.len() + pair.value.len();
+        if !batch.is_empty()
+            && (batch.len() >= KV_BULK_MAX_PAIRS || batch_bytes + pair_bytes > KV_BULK_MAX_BYTES)
+        {
+            batches.push(std::mem::take(&mut batch));
+            batch_bytes = 0;
+        }
+        batch_bytes += pair_bytes;
+        batch.push(pair);
+    }
+    if !batch.is_empty() {
+        batches.push(batch);
+    }
+    batches
+}
+
+/// Split `keys` into batches that stay under the pair-count limit of the
+/// Workers KV bulk delete endpoint.
+fn chunk_keys(keys: Vec<String>) -> Vec<Vec<String>> {
+    keys.chunks(KV_BULK_MAX_PAIRS)
+        .map(|chunk| chunk.to_vec())
+        .collect()
+}
+
+/// Retry `f` with exponential backoff (100ms, 200ms, 400ms, ...) so a
+/// transient 5xx doesn't abort an otherwise-successful deploy.
+fn with_exponential_backoff<T>(
+    mut f: impl FnMut() -> Result<T, failure::Error>,
+) -> Result<T, failure::Error> {
+    let mut delay = Duration::from_millis(100);
+    let mut last_err = None;
+    for _ in 0..=MAX_RETRIES {
+        match f() {
+            Ok(value) => return Ok(value),
+            Err(e) => {
+                last_err = Some(e);
+                thread::sleep(delay);
+                delay *= 2;
+            }
+        }
+    }
+    Err(last_err.unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http_client::{MockApiClient, MockResponse};
+
+    #[test]
+    fn it_returns_an_empty_set_for_an_empty_namespace() {
+        let client = MockApiClient::new(vec![MockResponse::Ok(serde_json::json!([]))]);
+        let store = KvStore::with_client("acct".to_string(), "ns".to_string(), client);
+
+        let keys = store.list_keys().unwrap();
+
+        assert!(keys.is_empty());
+    }
+
+    fn pair(key: &str, value_len: usize) -> KeyValuePair {
+        KeyValuePair {
+            key: key.to_string(),
+            value: "x".repeat(value_len),
+            expiration_ttl: None,
+            expiration: None,
+            base64: None,
+        }
+    }
+
+    #[test]
+    fn chunk_pairs_splits_once_the_pair_count_limit_is_hit() {
+        let pairs: Vec<_> = (0..KV_BULK_MAX_PAIRS + 1)
+            .map(|i| pair(&i.to_string(), 1))
+            .collect();
+
+        let batches = chunk_pairs(pairs);
+
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].len(), KV_BULK_MAX_PAIRS);
+        assert_eq!(batches[1].len(), 1);
+    }
+
+    #[test]
+    fn chunk_pairs_splits_once_the_byte_limit_is_hit() {
+        // Two pairs whose combined size just exceeds the byte cap must land
+        // in separate batches, even though there are only two of them.
+        let pairs = vec![pair("a", KV_BULK_MAX_BYTES - 1), pair("b", 2)];
+
+        let batches = chunk_pairs(pairs);
+
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].len(), 1);
+        assert_eq!(batches[1].len(), 1);
+    }
+
+    #[test]
+    fn chunk_pairs_keeps_a_single_undersized_batch_together() {
+        let pairs = vec![pair("a", 1), pair("b", 1), pair("c", 1)];
+
+        let batches = chunk_pairs(pairs);
+
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].len(), 3);
+    }
+
+    #[test]
+    fn chunk_keys_splits_at_the_pair_count_limit() {
+        let keys: Vec<_> = (0..KV_BULK_MAX_PAIRS + 1).map(|i| i.to_string()).collect();
+
+        let batches = chunk_keys(keys);
+
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].len(), KV_BULK_MAX_PAIRS);
+        assert_eq!(batches[1].len(), 1);
+    }
+
+    #[test]
+    fn with_exponential_backoff_returns_the_first_success() {
+        let mut calls = 0;
+        let result = with_exponential_backoff(|| {
+            calls += 1;
+            if calls < 3 {
+                Err(failure::format_err!("transient"))
+            } else {
+                Ok(calls)
+            }
+        })
+        .unwrap();
+
+        assert_eq!(result, 3);
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn with_exponential_backoff_gives_up_after_max_retries() {
+        let mut calls = 0;
+        let err = with_exponential_backoff::<()>(|| {
+            calls += 1;
+            Err(failure::format_err!("still failing"))
+        })
+        .unwrap_err();
+
+        // The initial attempt plus MAX_RETRIES retries.
+        assert_eq!(calls, MAX_RETRIES + 1);
+        assert!(err.to_string().contains("still failing"));
+    }
+}
@@ -0,0 +1,171 @@
+use regex::Regex;
+
+/// A single compiled glob pattern, tagged with whether a match means the
+/// path should be included or excluded.
+struct Pattern {
+    regex: Regex,
+    include: bool,
+}
+
+/// Matches asset paths against an ordered set of `.gitignore`-style glob
+/// patterns using last-match-wins semantics: patterns are evaluated in
+/// order, and whichever pattern matches last decides whether the path is
+/// included. A pattern prefixed with `!` is a negation -- it re-includes a
+/// path an earlier pattern excluded. Because patterns live in a single
+/// ordered list (rather than separate "include" and "exclude" arrays), the
+/// user controls interleaving just like in a real `.gitignore`: a later
+/// exclude can override an earlier `!`, and vice versa.
+///
+/// For backward compatibility with the old `subset` prefix filter, a
+/// non-empty `subset_str` is compiled into an implicit leading include
+/// pattern (`<subset_str>**`), and paths default to excluded unless they
+/// match it. With no subset and no patterns, everything is included, matching
+/// the old behavior of `Path::starts_with("")`.
+pub struct SiteIncludeExcludeMatcher {
+    patterns: Vec<Pattern>,
+    default_include: bool,
+}
+
+impl SiteIncludeExcludeMatcher {
+    /// `patterns` is an ordered list of globs relative to the site root. A
+    /// plain glob excludes matching paths; a glob prefixed with `!`
+    /// re-includes them. Patterns are evaluated in the order given.
+    pub fn new(
+        subset_str: &str,
+        patterns: &[String],
+    ) -> Result<SiteIncludeExcludeMatcher, failure::Error> {
+        let mut compiled = Vec::new();
+        let default_include = subset_str.is_empty();
+
+        if !subset_str.is_empty() {
+            compiled.push(Pattern {
+                regex: glob_to_regex(&format!("{}**", subset_str))?,
+                include: true,
+            });
+        }
+
+        for pattern in patterns {
+            let (glob, include) = match pattern.strip_prefix('!') {
+                Some(negated) => (negated, true),
+                None => (pattern.as_str(), false),
+            };
+            compiled.push(Pattern {
+                regex: glob_to_regex(glob)?,
+                include,
+            });
+        }
+
+        Ok(SiteIncludeExcludeMatcher {
+            patterns: compiled,
+            default_include,
+        })
+    }
+
+    /// Returns whether `path` (a `/`-separated path relative to the site
+    /// root) should be included in the upload/delete set.
+    pub fn is_match(&self, path: &str) -> bool {
+        let mut matched = self.default_include;
+        for pattern in &self.patterns {
+            if pattern.regex.is_match(path) {
+                matched = pattern.include;
+            }
+        }
+        matched
+    }
+}
+
+/// Translate a `.gitignore`-style glob into an anchored regex: `*` matches
+/// within a path segment, `**` matches across segments, and everything else
+/// is matched literally. A glob with no `/` in it (e.g. `*.map`) matches at
+/// any depth, exactly like `.gitignore` treats a slash-less pattern.
+fn glob_to_regex(glob: &str) -> Result<Regex, failure::Error> {
+    let body = glob_to_regex_body(glob);
+    let anchored = if glob.contains('/') {
+        format!("^{}$", body)
+    } else {
+        format!("^(?:.*/)?{}$", body)
+    };
+
+    Regex::new(&anchored)
+        .map_err(|e| failure::format_err!("invalid glob pattern `{}`: {}", glob, e))
+}
+
+fn glob_to_regex_body(glob: &str) -> String {
+    let mut regex_str = String::new();
+    let mut chars = glob.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => {
+                if chars.peek() == Some(&'*') {
+                    chars.next();
+                    regex_str.push_str(".*");
+                } else {
+                    regex_str.push_str("[^/]*");
+                }
+            }
+            '?' => regex_str.push_str("[^/]"),
+            '.' | '+' | '(' | ')' | '|' | '^' | '$' | '[' | ']' | '{' | '}' | '\\' => {
+                regex_str.push('\\');
+                regex_str.push(c);
+            }
+            _ => regex_str.push(c),
+        }
+    }
+    regex_str
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_matches_plain_prefix_like_old_subset_behavior() {
+        let matcher = SiteIncludeExcludeMatcher::new("blog/", &[]).unwrap();
+        assert!(matcher.is_match("blog/index.html"));
+        assert!(!matcher.is_match("admin/index.html"));
+    }
+
+    #[test]
+    fn it_excludes_with_double_star() {
+        let matcher =
+            SiteIncludeExcludeMatcher::new("", &["node_modules/**".to_string()]).unwrap();
+        assert!(!matcher.is_match("node_modules/foo/bar.js"));
+        assert!(matcher.is_match("src/index.js"));
+    }
+
+    #[test]
+    fn a_slash_less_glob_excludes_at_any_depth() {
+        let matcher = SiteIncludeExcludeMatcher::new("", &["*.map".to_string()]).unwrap();
+        assert!(!matcher.is_match("app.js.map"));
+        assert!(!matcher.is_match("dist/app.js.map"));
+        assert!(!matcher.is_match("dist/nested/app.js.map"));
+        assert!(matcher.is_match("dist/app.js"));
+    }
+
+    #[test]
+    fn a_later_negation_re_includes_an_earlier_exclusion() {
+        let matcher = SiteIncludeExcludeMatcher::new(
+            "",
+            &["*.map".to_string(), "!dist/keep.map".to_string()],
+        )
+        .unwrap();
+        // The negation comes after the broad exclude, so it wins for this
+        // specific file...
+        assert!(matcher.is_match("dist/keep.map"));
+        // ...while other `.map` files stay excluded.
+        assert!(!matcher.is_match("dist/app.js.map"));
+    }
+
+    #[test]
+    fn a_later_exclusion_overrides_an_earlier_negation() {
+        let matcher = SiteIncludeExcludeMatcher::new(
+            "",
+            &["!dist/keep.map".to_string(), "*.map".to_string()],
+        )
+        .unwrap();
+        // This time the broad exclude is declared after the negation, so it
+        // wins -- the user controls precedence via pattern order.
+        assert!(!matcher.is_match("dist/keep.map"));
+    }
+}
@@ -0,0 +1,325 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use cloudflare::endpoints::workerskv::write_bulk::KeyValuePair;
+use indicatif::{MultiProgress, ProgressBar};
+use rusoto_core::{HttpClient, Region};
+use rusoto_credential::StaticProvider;
+use rusoto_s3::{
+    Delete, DeleteObjectsRequest, ListObjectsV2Request, ObjectIdentifier, PutObjectRequest, S3Client, S3,
+};
+
+use crate::settings::toml::Target;
+
+use super::store::{progress_style, SiteStore};
+
+// How many objects we'll PUT/DELETE concurrently. S3 has no bulk-write
+// endpoint like Workers KV, so uploads are one PUT per object; this keeps us
+// from opening an unbounded number of connections on a large site.
+const MAX_CONCURRENT_REQUESTS: usize = 10;
+
+// The S3 API caps a single `DeleteObjects` call at 1000 keys.
+const S3_DELETE_BATCH_SIZE: usize = 1_000;
+
+/// A `SiteStore` backed by an S3-compatible object store, for sites that
+/// want their assets to live outside Workers KV.
+pub struct S3Store {
+    bucket: String,
+    // Prepended to every object key this store touches, so a site sharing a
+    // bucket with other tenants can be scoped to its own namespace -- without
+    // it, `to_delete` (computed as remote-minus-local) could delete objects
+    // this site never wrote.
+    prefix: String,
+    client: S3Client,
+}
+
+impl S3Store {
+    pub fn from_target(target: &Target) -> Result<S3Store, failure::Error> {
+        let site_config = target
+            .site
+            .as_ref()
+            .ok_or_else(|| failure::format_err!("`[site]` config is required for the `s3` backend"))?;
+
+        let bucket = site_config
+            .bucket
+            .clone()
+            .ok_or_else(|| failure::format_err!("`[site] bucket` is required for the `s3` backend"))?;
+        let prefix = site_config.key_prefix.clone().unwrap_or_default();
+
+        let region = match &site_config.endpoint {
+            Some(endpoint) => Region::Custom {
+                name: site_config.region.clone().unwrap_or_else(|| "us-east-1".to_string()),
+                endpoint: endpoint.clone(),
+            },
+            None => site_config
+                .region
+                .as_deref()
+                .map(str::parse)
+                .transpose()
+                .map_err(|e| failure::format_err!("invalid `[site] region`: {}", e))?
+                .unwrap_or(Region::UsEast1),
+        };
+
+        // `[site] access_key_id`/`secret_access_key` let a user keep S3
+        // credentials alongside the rest of the site config in
+        // wrangler.toml, instead of only through rusoto's ambient chain
+        // (env vars/instance role), which is still used when they're unset.
+        let client = match (&site_config.access_key_id, &site_config.secret_access_key) {
+            (Some(access_key_id), Some(secret_access_key)) => {
+                let credentials = StaticProvider::new_minimal(access_key_id.clone(), secret_access_key.clone());
+                let http_client = HttpClient::new()
+                    .map_err(|e| failure::format_err!("failed to build S3 HTTP client: {}", e))?;
+                S3Client::new_with(http_client, credentials, region)
+            }
+            _ => S3Client::new(region),
+        };
+
+        Ok(S3Store {
+            bucket,
+            prefix,
+            client,
+        })
+    }
+
+    fn object_key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+}
+
+impl SiteStore for S3Store {
+    fn list_keys(&self) -> Result<HashSet<String>, failure::Error> {
+        let mut keys = HashSet::new();
+        let mut continuation_token = None;
+
+        loop {
+            let request = ListObjectsV2Request {
+                bucket: self.bucket.clone(),
+                prefix: Some(self.prefix.clone()),
+                continuation_token: continuation_token.clone(),
+                ..Default::default()
+            };
+            let response = self
+                .client
+                .list_objects_v2(request)
+                .sync()
+                .map_err(|e| failure::format_err!("failed to list S3 objects: {}", e))?;
+
+            for object in response.contents.unwrap_or_default() {
+                if let Some(key) = object.key.and_then(|key| key.strip_prefix(&self.prefix).map(str::to_string)) {
+                    keys.insert(key);
+                }
+            }
+
+            continuation_token = response.next_continuation_token;
+            if continuation_token.is_none() {
+                break;
+            }
+        }
+
+        Ok(keys)
+    }
+
+    fn bulk_put(&self, pairs: Vec<KeyValuePair>, multi_progress: &MultiProgress) -> Result<(), failure::Error> {
+        let progress = ProgressBar::new(pairs.len() as u64);
+        progress.set_style(progress_style("objects uploaded"));
+        let progress = multi_progress.add(progress);
+
+        let errors: Arc<Mutex<Vec<failure::Error>>> = Arc::new(Mutex::new(Vec::new()));
+
+        thread::scope(|scope| {
+            for group in pairs.chunks(MAX_CONCURRENT_REQUESTS) {
+                let handles: Vec<_> = group
+                    .iter()
+                    .map(|pair| {
+                        let pair = pair.clone();
+                        let progress = progress.clone();
+                        let errors = Arc::clone(&errors);
+                        scope.spawn(move || {
+                            let body = match object_body(&pair) {
+                                Ok(body) => body,
+                                Err(e) => {
+                                    errors.lock().unwrap().push(e);
+                                    return;
+                                }
+                            };
+                            let request = PutObjectRequest {
+                                bucket: self.bucket.clone(),
+                                key: self.object_key(&pair.key),
+                                body: Some(body.into()),
+                                ..Default::default()
+                            };
+                            match self.client.put_object(request).sync() {
+                                Ok(_) => progress.inc(1),
+                                Err(e) => errors
+                                    .lock()
+                                    .unwrap()
+                                    .push(failure::format_err!("failed to upload {}: {}", pair.key, e)),
+                            }
+                        })
+                    })
+                    .collect();
+                for handle in handles {
+                    let _ = handle.join();
+                }
+            }
+        });
+
+        progress.finish_and_clear();
+        into_result(errors, "upload")
+    }
+
+    fn bulk_delete(&self, keys: Vec<String>, multi_progress: &MultiProgress) -> Result<(), failure::Error> {
+        let progress = ProgressBar::new(keys.len() as u64);
+        progress.set_style(progress_style("objects deleted"));
+        let progress = multi_progress.add(progress);
+
+        let errors: Arc<Mutex<Vec<failure::Error>>> = Arc::new(Mutex::new(Vec::new()));
+
+        for batch in keys.chunks(S3_DELETE_BATCH_SIZE) {
+            let request = DeleteObjectsRequest {
+                bucket: self.bucket.clone(),
+                delete: Delete {
+                    objects: batch
+                        .iter()
+                        .map(|key| ObjectIdentifier {
+                            key: self.object_key(key),
+                            version_id: None,
+                        })
+                        .collect(),
+                    // Not `quiet`: a 200 response doesn't mean every object
+                    // in the batch was deleted, and we need the per-object
+                    // `errors` below to tell a partial failure from a full
+                    // success instead of just trusting the outer `Ok`.
+                    quiet: None,
+                },
+                ..Default::default()
+            };
+            match self.client.delete_objects(request).sync() {
+                Ok(response) => {
+                    progress.inc(response.deleted.map_or(0, |deleted| deleted.len()) as u64);
+                    for error in response.errors.unwrap_or_default() {
+                        errors.lock().unwrap().push(failure::format_err!(
+                            "failed to delete {}: {}",
+                            error.key.unwrap_or_default(),
+                            error.message.unwrap_or_default()
+                        ));
+                    }
+                }
+                Err(e) => errors
+                    .lock()
+                    .unwrap()
+                    .push(failure::format_err!("failed to delete batch: {}", e)),
+            }
+        }
+
+        progress.finish_and_clear();
+        into_result(errors, "delete")
+    }
+}
+
+/// `pair.value` is base64 text for binary assets (see
+/// `directory_keys_values`); decode it back to bytes before writing the
+/// object, or S3 would store the base64 text itself as the body.
+fn object_body(pair: &KeyValuePair) -> Result<Vec<u8>, failure::Error> {
+    if pair.base64 == Some(true) {
+        base64::decode(&pair.value)
+            .map_err(|e| failure::format_err!("invalid base64 value for {}: {}", pair.key, e))
+    } else {
+        Ok(pair.value.clone().into_bytes())
+    }
+}
+
+fn into_result(
+    errors: Arc<Mutex<Vec<failure::Error>>>,
+    verb: &str,
+) -> Result<(), failure::Error> {
+    let errors = Arc::try_unwrap(errors).unwrap().into_inner().unwrap();
+    if !errors.is_empty() {
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        failure::bail!(
+            "{} object(s) failed to {} in S3:\n{}",
+            messages.len(),
+            verb,
+            messages.join("\n")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(prefix: &str) -> S3Store {
+        S3Store {
+            bucket: "bucket".to_string(),
+            prefix: prefix.to_string(),
+            client: S3Client::new(Region::UsEast1),
+        }
+    }
+
+    #[test]
+    fn object_key_prepends_the_configured_prefix() {
+        assert_eq!(store("site/").object_key("index.html"), "site/index.html");
+        assert_eq!(store("").object_key("index.html"), "index.html");
+    }
+
+    #[test]
+    fn object_body_passes_plain_text_through_unchanged() {
+        let pair = KeyValuePair {
+            key: "a".to_string(),
+            value: "hello".to_string(),
+            expiration_ttl: None,
+            expiration: None,
+            base64: None,
+        };
+
+        assert_eq!(object_body(&pair).unwrap(), b"hello".to_vec());
+    }
+
+    #[test]
+    fn object_body_decodes_base64_encoded_binary_assets() {
+        let pair = KeyValuePair {
+            key: "a".to_string(),
+            value: base64::encode(&[0xff, 0x00, 0x10]),
+            expiration_ttl: None,
+            expiration: None,
+            base64: Some(true),
+        };
+
+        assert_eq!(object_body(&pair).unwrap(), vec![0xff, 0x00, 0x10]);
+    }
+
+    #[test]
+    fn object_body_surfaces_invalid_base64_as_an_error() {
+        let pair = KeyValuePair {
+            key: "a".to_string(),
+            value: "not valid base64!!".to_string(),
+            expiration_ttl: None,
+            expiration: None,
+            base64: Some(true),
+        };
+
+        let err = object_body(&pair).unwrap_err();
+        assert!(err.to_string().contains("invalid base64 value for a"));
+    }
+
+    #[test]
+    fn into_result_is_ok_when_no_errors_were_collected() {
+        let errors: Arc<Mutex<Vec<failure::Error>>> = Arc::new(Mutex::new(Vec::new()));
+        assert!(into_result(errors, "upload").is_ok());
+    }
+
+    #[test]
+    fn into_result_surfaces_a_partial_delete_failure() {
+        let errors: Arc<Mutex<Vec<failure::Error>>> = Arc::new(Mutex::new(vec![failure::format_err!(
+            "failed to delete a.txt: Access Denied"
+        )]));
+
+        let err = into_result(errors, "delete").unwrap_err();
+
+        assert!(err.to_string().contains("1 object(s) failed to delete"));
+        assert!(err.to_string().contains("a.txt: Access Denied"));
+    }
+}
@@ -0,0 +1,58 @@
+use std::collections::HashSet;
+
+use cloudflare::endpoints::workerskv::write_bulk::KeyValuePair;
+use indicatif::{MultiProgress, ProgressStyle};
+
+use crate::settings::global_user::GlobalUser;
+use crate::settings::toml::Target;
+
+use super::kv_store::KvStore;
+use super::s3_store::S3Store;
+
+/// A backend capable of storing a site's assets. Workers KV is the default
+/// and only backend wrangler originally supported; implementing this trait
+/// lets `sync()` treat any other object store (e.g. S3) the same way.
+pub trait SiteStore {
+    /// All keys currently present in the store, including their
+    /// content-hash suffix.
+    fn list_keys(&self) -> Result<HashSet<String>, failure::Error>;
+
+    /// Write `pairs` to the store. Implementations are responsible for
+    /// chunking, concurrency, and retries appropriate to their transport.
+    /// `multi_progress` is the display the implementation adds its progress
+    /// bar to; `sync::upload` passes the same one to the paired
+    /// `bulk_delete` call, which runs after this one has returned.
+    fn bulk_put(&self, pairs: Vec<KeyValuePair>, multi_progress: &MultiProgress) -> Result<(), failure::Error>;
+
+    /// Remove `keys` from the store. See `bulk_put` on `multi_progress`.
+    fn bulk_delete(&self, keys: Vec<String>, multi_progress: &MultiProgress) -> Result<(), failure::Error>;
+}
+
+/// Build the `SiteStore` selected by `[site] backend` in `wrangler.toml`
+/// (`"kv"` by default, or `"s3"`).
+pub fn build_store(
+    target: &Target,
+    user: &GlobalUser,
+    namespace_id: &str,
+) -> Result<Box<dyn SiteStore>, failure::Error> {
+    let backend = target
+        .site
+        .as_ref()
+        .and_then(|site| site.backend.as_deref())
+        .unwrap_or("kv");
+
+    match backend {
+        "kv" => Ok(Box::new(KvStore::new(target.clone(), user.clone(), namespace_id.to_string())?)),
+        "s3" => Ok(Box::new(S3Store::from_target(target)?)),
+        other => failure::bail!(
+            "unknown `[site] backend = \"{}\"`; expected \"kv\" or \"s3\"",
+            other
+        ),
+    }
+}
+
+pub(super) fn progress_style(unit_name: &str) -> ProgressStyle {
+    ProgressStyle::default_bar()
+        .template(&format!("{{msg}} [{{bar:40.cyan/blue}}] {{pos}}/{{len}} {}", unit_name))
+        .progress_chars("=> ")
+}
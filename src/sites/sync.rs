@@ -6,59 +6,75 @@ use cloudflare::endpoints::workerskv::write_bulk::KeyValuePair;
 use super::directory_keys_values;
 use super::remove_hash_from_path;
 use super::manifest::AssetManifest;
+use super::pattern::SiteIncludeExcludeMatcher;
+use super::store::SiteStore;
 use crate::commands::kv;
-use crate::http;
-use crate::kv::key::KeyList;
-use crate::settings::global_user::GlobalUser;
 use crate::settings::toml::Target;
 use crate::terminal::message::{Message, StdErr};
+use indicatif::{MultiProgress, ProgressBar};
 
+/// Compute the upload/delete plan for syncing `path` against `store`, and
+/// return it alongside the rewritten `AssetManifest`. `store` determines
+/// where "remote" actually lives (Workers KV, S3, ...); this function is
+/// agnostic to the concrete backend.
 pub fn sync(
+    store: &dyn SiteStore,
     target: &Target,
-    user: &GlobalUser,
-    namespace_id: &str,
     path: &Path,
 ) -> Result<(Vec<KeyValuePair>, Vec<String>, AssetManifest), failure::Error> {
     kv::validate_target(target)?;
-    let subset = if let Some(site_config) = target.site.clone() {
-        site_config.subset
-    } else {
-        None
-    };
-    let subset_str = subset.as_deref().unwrap_or("");
-    
+    let site_config = target.site.clone();
+    let subset_str = site_config
+        .as_ref()
+        .and_then(|c| c.subset.as_deref())
+        .unwrap_or("");
+    // `[site] include` predates the gitignore-style `exclude` list and has
+    // no equivalent in it; rather than silently ignore it, reject it so a
+    // user relying on it finds out instead of getting an unfiltered upload.
+    if site_config.as_ref().map_or(false, |c| c.include.as_ref().map_or(false, |i| !i.is_empty())) {
+        failure::bail!(
+            "`[site] include` is no longer supported; use `[site] exclude`, \
+             with a leading `!` on a pattern to re-include a path excluded \
+             by an earlier one."
+        );
+    }
+    let empty = Vec::new();
+    // An ordered list of globs relative to the site root. A plain glob
+    // excludes matching paths; a glob prefixed with `!` re-includes them,
+    // so users control precedence by ordering, same as a `.gitignore`.
+    let patterns = site_config.as_ref().and_then(|c| c.exclude.as_ref()).unwrap_or(&empty);
+    let matcher = SiteIncludeExcludeMatcher::new(subset_str, patterns)?;
+
     // First, find all changed files in given local directory (aka files that are now stale
-    // in Workers KV).
+    // in the store).
 
     // Get remote keys, which contain the hash of the file (value) as the suffix.
     // Turn it into a HashSet. This will be used by upload() to figure out which
     // files to exclude from upload (because their current version already exists in
-    // the Workers KV remote).
-    let client = http::cf_v4_client(&user)?;
-    let remote_keys_iter = KeyList::new(target, client, namespace_id, None)?;
-    let mut remote_keys: HashSet<String> = HashSet::new();
-    for remote_key in remote_keys_iter {
-        match remote_key {
-            Ok(remote_key) => {
-                remote_keys.insert(remote_key.name);
-            }
-            Err(e) => failure::bail!(kv::format_error(e)),
-        }
-    }
-    let remote_subset =  subset_keys(&remote_keys, &subset_str);
+    // the remote store).
+    let remote_keys = store.list_keys()?;
+    let remote_subset = subset_keys(&remote_keys, &matcher);
 
+    let hashing_progress = ProgressBar::new_spinner();
+    hashing_progress.set_message("hashing and collecting assets...");
+    hashing_progress.enable_steady_tick(100);
+    // `directory_keys_values` walks the whole directory unfiltered; matcher
+    // exclusion is applied afterward (`subset_keys`, `filter_files`, and,
+    // when a subset is set, `preserve_remote_hashes_outside_subset`) rather
+    // than during the walk.
     let (pairs, mut asset_manifest, _): (Vec<KeyValuePair>, AssetManifest, _) =
         directory_keys_values(target, path)?;
+    hashing_progress.finish_and_clear();
 
-    // Now delete files from Workers KV that exist in remote but no longer exist locally.
+    // Now delete files from the store that exist in remote but no longer exist locally.
     // Get local keys
     let mut local_keys: HashSet<_> = HashSet::new();
     for pair in pairs.iter() {
         local_keys.insert(pair.key.clone());
     }
-    let local_subset = subset_keys(&local_keys, &subset_str);
+    let local_subset = subset_keys(&local_keys, &matcher);
 
-    let to_upload = filter_files(pairs.clone(), &remote_subset, &subset_str);
+    let to_upload = filter_files(pairs.clone(), &remote_subset, &matcher);
     // Find keys that are present in remote but not present in local, and
     // stage them for deletion.
     let to_delete: Vec<_> = remote_subset
@@ -67,36 +83,71 @@ pub fn sync(
         .collect();
 
     if !subset_str.is_empty() {
-        for (key,val) in asset_manifest.iter_mut() {
-             if !Path::new(&key).starts_with(&subset_str) {
-                if let Some(original) = remote_keys.iter().find(|&k| {
-                    key == &remove_hash_from_path(Path::new(&k)).unwrap()
-                }) {
-                    if val != original {
-                        *val = String::from(original);
-                    }
-                }
-            }  
-        }
+        preserve_remote_hashes_outside_subset(asset_manifest.iter_mut(), &remote_keys, &matcher);
     }
     StdErr::success("Success");
     Ok((to_upload, to_delete, asset_manifest))
 }
 
-fn filter_files(pairs: Vec<KeyValuePair>, already_uploaded: &HashSet<String>, subset_str: &str) -> Vec<KeyValuePair> {
+/// When syncing a subset of the site, keys outside that subset weren't
+/// touched by this sync -- so their manifest entry must keep reflecting
+/// whatever is actually live on the remote, rather than whatever hash the
+/// (unsynced) local file happens to have right now.
+fn preserve_remote_hashes_outside_subset<'a>(
+    entries: impl Iterator<Item = (&'a String, &'a mut String)>,
+    remote_keys: &HashSet<String>,
+    matcher: &SiteIncludeExcludeMatcher,
+) {
+    for (key, val) in entries {
+        if !matcher.is_match(key) {
+            if let Some(original) = remote_keys
+                .iter()
+                .find(|&k| key == &remove_hash_from_path(Path::new(&k)).unwrap())
+            {
+                if val != original {
+                    *val = String::from(original);
+                }
+            }
+        }
+    }
+}
+
+/// Upload `to_upload` and delete `to_delete` through `store`. Chunking,
+/// concurrency, retries, and progress reporting are all the responsibility
+/// of the concrete `SiteStore` implementation; this just hands both calls a
+/// shared `MultiProgress`. The two calls run one after the other: the upload
+/// bar is finished and cleared before the delete bar is added.
+pub fn upload(
+    store: &dyn SiteStore,
+    to_upload: Vec<KeyValuePair>,
+    to_delete: Vec<String>,
+) -> Result<(), failure::Error> {
+    let multi_progress = MultiProgress::new();
+    store.bulk_put(to_upload, &multi_progress)?;
+    store.bulk_delete(to_delete, &multi_progress)?;
+    Ok(())
+}
+
+fn filter_files(
+    pairs: Vec<KeyValuePair>,
+    already_uploaded: &HashSet<String>,
+    matcher: &SiteIncludeExcludeMatcher,
+) -> Vec<KeyValuePair> {
     let mut filtered_pairs: Vec<KeyValuePair> = Vec::new();
     for pair in pairs {
-        if Path::new(&pair.key).starts_with(&subset_str) && !already_uploaded.contains(&pair.key) {
+        let rel_path = remove_hash_from_path(Path::new(&pair.key)).unwrap_or_else(|_| pair.key.clone());
+        if matcher.is_match(&rel_path) && !already_uploaded.contains(&pair.key) {
             filtered_pairs.push(pair);
         }
     }
     filtered_pairs
 }
 
-fn subset_keys(keys: &HashSet<String>, subset_str: &str) -> HashSet<String> {
+fn subset_keys(keys: &HashSet<String>, matcher: &SiteIncludeExcludeMatcher) -> HashSet<String> {
     let mut filtered_keys: HashSet<String> = HashSet::new();
     for key in keys {
-        if Path::new(&key).starts_with(&subset_str) {
+        let rel_path = remove_hash_from_path(Path::new(key)).unwrap_or_else(|_| key.clone());
+        if matcher.is_match(&rel_path) {
             filtered_keys.insert(key.clone());
         }
     }
@@ -110,6 +161,39 @@ mod tests {
     use std::collections::HashSet;
     use std::path::Path;
 
+    #[test]
+    fn it_preserves_remote_hash_for_keys_outside_the_subset() {
+        let (_, admin_key_remote) = generate_path_and_key(
+            Path::new("/admin/index.html"),
+            Path::new("/"),
+            Some("remote".to_string()),
+        )
+        .unwrap();
+        let mut remote_keys = HashSet::new();
+        remote_keys.insert(admin_key_remote.clone());
+
+        let matcher = SiteIncludeExcludeMatcher::new("blog/", &[]).unwrap();
+
+        let mut manifest = std::collections::BTreeMap::new();
+        manifest.insert(
+            "admin/index.html".to_string(),
+            "admin/index.html.stale".to_string(),
+        );
+        manifest.insert(
+            "blog/index.html".to_string(),
+            "blog/index.html.uptodate".to_string(),
+        );
+
+        preserve_remote_hashes_outside_subset(manifest.iter_mut(), &remote_keys, &matcher);
+
+        // Outside the synced subset, the manifest must reflect what's
+        // actually live on remote...
+        assert_eq!(manifest["admin/index.html"], admin_key_remote);
+        // ...while entries inside the subset are left for the rest of
+        // sync() to manage.
+        assert_eq!(manifest["blog/index.html"], "blog/index.html.uptodate");
+    }
+
     #[test]
     fn it_can_filter_preexisting_files() {
         let (_, key_a_old) =
@@ -153,7 +237,8 @@ mod tests {
             expiration: None,
             base64: None,
         }];
-        let actual = filter_files(pairs_to_upload, &exclude_keys, "");
+        let matcher = SiteIncludeExcludeMatcher::new("", &[]).unwrap();
+        let actual = filter_files(pairs_to_upload, &exclude_keys, &matcher);
         check_kv_pairs_equality(expected, actual);
     }
 
@@ -0,0 +1,215 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::channel;
+use std::time::Duration;
+
+use cloudflare::endpoints::workerskv::write_bulk::KeyValuePair;
+use notify::{watcher, DebouncedEvent, RecursiveMode, Watcher};
+
+use super::generate_path_and_key;
+use super::manifest::AssetManifest;
+use super::store::SiteStore;
+use super::sync;
+use crate::terminal::message::{Message, StdOut};
+
+// notify's recommended debounce window; bursts of writes from editors/build
+// tools land inside a single window instead of triggering a resync per file.
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+// The well-known key the initial sync pass publishes the serialized
+// `AssetManifest` under, so Workers can look up the hashed asset key for a
+// given path at request time.
+const MANIFEST_KEY: &str = "__STATIC_CONTENT_MANIFEST";
+
+/// Watch `path` for filesystem changes and keep `store` incrementally in
+/// sync, instead of recomputing and re-uploading the full manifest on every
+/// deploy.
+///
+/// `asset_manifest` and `remote_keys` should be the results of the initial
+/// `sync()` pass; this function seeds its `path -> content-hash` cache from
+/// `asset_manifest` and then only re-hashes and re-uploads the files that
+/// actually change. `remote_keys` is updated as keys are put and deleted,
+/// but is not consulted when deciding what to upload.
+pub fn watch(
+    store: &dyn SiteStore,
+    path: &Path,
+    mut asset_manifest: AssetManifest,
+    mut remote_keys: HashSet<String>,
+) -> Result<(), failure::Error> {
+    let mut key_hashes: HashMap<PathBuf, String> = HashMap::new();
+    for (rel_path, kv_key) in asset_manifest.iter() {
+        if let Some(hash) = hash_suffix(kv_key) {
+            key_hashes.insert(PathBuf::from(rel_path), hash);
+        }
+    }
+
+    let (tx, rx) = channel();
+    let mut watcher = watcher(tx, DEBOUNCE)?;
+    watcher.watch(path, RecursiveMode::Recursive)?;
+
+    StdOut::message(&format!(
+        "Watching {} for changes. Press Ctrl-C to stop.",
+        path.display()
+    ));
+
+    loop {
+        let event = rx
+            .recv()
+            .map_err(|e| failure::format_err!("file watcher disconnected: {}", e))?;
+
+        let manifest_changed = handle_event(
+            store,
+            path,
+            event,
+            &mut key_hashes,
+            &mut remote_keys,
+            &mut asset_manifest,
+        )?;
+
+        if manifest_changed {
+            upload_manifest(store, &asset_manifest)?;
+        }
+    }
+}
+
+/// Handle a single debounced filesystem event, issuing a targeted bulk-put
+/// or bulk-delete as needed. Returns whether the key set changed (and so the
+/// `AssetManifest` needs to be rewritten and re-uploaded).
+fn handle_event(
+    store: &dyn SiteStore,
+    root: &Path,
+    event: DebouncedEvent,
+    key_hashes: &mut HashMap<PathBuf, String>,
+    remote_keys: &mut HashSet<String>,
+    asset_manifest: &mut AssetManifest,
+) -> Result<bool, failure::Error> {
+    match event {
+        DebouncedEvent::Create(changed) | DebouncedEvent::Write(changed) => {
+            Ok(resync_file(store, root, &changed, key_hashes, remote_keys, asset_manifest)?)
+        }
+        DebouncedEvent::Rename(old, new) => {
+            let removed = remove_file(store, root, &old, key_hashes, remote_keys, asset_manifest)?;
+            let added = resync_file(store, root, &new, key_hashes, remote_keys, asset_manifest)?;
+            Ok(removed || added)
+        }
+        DebouncedEvent::Remove(removed) => Ok(remove_file(
+            store,
+            root,
+            &removed,
+            key_hashes,
+            remote_keys,
+            asset_manifest,
+        )?),
+        // Rescans, permission changes, etc. don't change what's on the store.
+        _ => Ok(false),
+    }
+}
+
+fn resync_file(
+    store: &dyn SiteStore,
+    root: &Path,
+    changed: &Path,
+    key_hashes: &mut HashMap<PathBuf, String>,
+    remote_keys: &mut HashSet<String>,
+    asset_manifest: &mut AssetManifest,
+) -> Result<bool, failure::Error> {
+    if !changed.is_file() {
+        return Ok(false);
+    }
+    // Match the encoding `directory_keys_values` uses for the initial sync
+    // pass: text content is uploaded as-is, anything that isn't valid UTF-8
+    // (images, fonts, ...) is base64-encoded instead of being mangled.
+    let bytes = std::fs::read(changed)?;
+    let (value, base64) = match String::from_utf8(bytes) {
+        Ok(text) => (text, None),
+        Err(e) => (base64::encode(e.as_bytes()), Some(true)),
+    };
+    let (_, new_key) = generate_path_and_key(changed, root, Some(value.clone()))?;
+    let new_hash = match hash_suffix(&new_key) {
+        Some(hash) => hash,
+        None => return Ok(false),
+    };
+    let rel_path = changed
+        .strip_prefix(root)
+        .unwrap_or(changed)
+        .to_path_buf();
+
+    if key_hashes.get(&rel_path) == Some(&new_hash) {
+        // Content is unchanged (e.g. a save that didn't change bytes); skip
+        // the redundant put.
+        return Ok(false);
+    }
+
+    let old_key = asset_manifest.get(&rel_path.to_string_lossy().to_string()).cloned();
+
+    sync::upload(
+        store,
+        vec![KeyValuePair {
+            key: new_key.clone(),
+            value,
+            expiration: None,
+            expiration_ttl: None,
+            base64,
+        }],
+        old_key
+            .filter(|old_key| old_key != &new_key)
+            .into_iter()
+            .collect(),
+    )?;
+
+    if let Some(old_key) = asset_manifest.insert(rel_path.to_string_lossy().to_string(), new_key.clone()) {
+        remote_keys.remove(&old_key);
+    }
+    remote_keys.insert(new_key);
+    key_hashes.insert(rel_path, new_hash);
+
+    Ok(true)
+}
+
+fn remove_file(
+    store: &dyn SiteStore,
+    root: &Path,
+    removed: &Path,
+    key_hashes: &mut HashMap<PathBuf, String>,
+    remote_keys: &mut HashSet<String>,
+    asset_manifest: &mut AssetManifest,
+) -> Result<bool, failure::Error> {
+    let rel_path = removed.strip_prefix(root).unwrap_or(removed).to_path_buf();
+    let rel_path_str = rel_path.to_string_lossy().to_string();
+
+    let kv_key = match asset_manifest.remove(&rel_path_str) {
+        Some(kv_key) => kv_key,
+        None => return Ok(false),
+    };
+
+    sync::upload(store, Vec::new(), vec![kv_key.clone()])?;
+
+    remote_keys.remove(&kv_key);
+    key_hashes.remove(&rel_path);
+
+    Ok(true)
+}
+
+/// Publish the current `AssetManifest` under `MANIFEST_KEY` through `store`,
+/// the same way the initial sync pass does -- writing it directly via
+/// `sync::upload` rather than through an `AssetManifest::upload` method tied
+/// to a specific KV namespace, so this keeps working for any `SiteStore`.
+fn upload_manifest(store: &dyn SiteStore, asset_manifest: &AssetManifest) -> Result<(), failure::Error> {
+    sync::upload(
+        store,
+        vec![KeyValuePair {
+            key: MANIFEST_KEY.to_string(),
+            value: serde_json::to_string(asset_manifest)?,
+            expiration: None,
+            expiration_ttl: None,
+            base64: None,
+        }],
+        Vec::new(),
+    )
+}
+
+/// Site keys are generated as `<path>.<hash>`; pull the hash back out so we
+/// can compare it against a freshly-hashed file.
+fn hash_suffix(kv_key: &str) -> Option<String> {
+    kv_key.rsplit('.').next().map(str::to_string)
+}